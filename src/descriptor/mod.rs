@@ -0,0 +1,112 @@
+//! Cross-platform HID report descriptor types.
+//!
+//! [`parser`] turns a raw descriptor byte blob (as returned by `hidraw` on
+//! Linux, or obtained from any other source) into the [`ReportDescriptor`]
+//! tree defined here. This is the inverse of the reconstruction done from
+//! Windows preparsed data in `crate::windows_native::descriptor`.
+
+pub mod parser;
+pub mod units;
+
+pub type Usage = u16;
+
+/// The three kinds of HID report a field can belong to.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum ReportType {
+    Input,
+    Output,
+    Feature
+}
+
+/// The type carried by a Main item's `Collection` tag.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum CollectionType {
+    Physical,
+    Application,
+    Logical,
+    Report,
+    NamedArray,
+    UsageSwitch,
+    UsageModifier,
+    /// Any value reserved for vendor use (0x07..=0x7F) or reserved by the
+    /// spec (0x80..=0xFF), kept verbatim.
+    Other(u8)
+}
+
+impl CollectionType {
+    fn from_raw(value: u8) -> Self {
+        match value {
+            0x00 => CollectionType::Physical,
+            0x01 => CollectionType::Application,
+            0x02 => CollectionType::Logical,
+            0x03 => CollectionType::Report,
+            0x04 => CollectionType::NamedArray,
+            0x05 => CollectionType::UsageSwitch,
+            0x06 => CollectionType::UsageModifier,
+            other => CollectionType::Other(other)
+        }
+    }
+}
+
+/// Either the single usage set by a `Usage` local item, or the range set by
+/// a `Usage Minimum`/`Usage Maximum` pair.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum FieldUsage {
+    Single(Usage),
+    Range(Usage, Usage)
+}
+
+/// One Input/Output/Feature main item, combined with the global state that
+/// was in effect when it was emitted.
+#[derive(Clone, Debug)]
+pub struct Field {
+    pub report_type: ReportType,
+    pub report_id: u8,
+    pub usage_page: Usage,
+    pub usage: FieldUsage,
+    pub logical_min: i32,
+    pub logical_max: i32,
+    pub physical_min: i32,
+    pub physical_max: i32,
+    pub unit: u32,
+    pub unit_exponent: u32,
+    pub report_size: u16,
+    pub report_count: u16,
+    /// Offset, in bits, of this field within its report (report id byte not
+    /// included), as it appeared while walking the descriptor.
+    pub bit_offset: u32,
+    /// Raw main item data byte(s): Constant/Variable/Relative/Wrap/... flags.
+    pub flags: u32
+}
+
+impl Field {
+    pub fn is_constant(&self) -> bool {
+        self.flags & 0x1 != 0
+    }
+    pub fn is_variable(&self) -> bool {
+        self.flags & 0x2 != 0
+    }
+    pub fn is_relative(&self) -> bool {
+        self.flags & 0x4 != 0
+    }
+}
+
+/// A node of the parsed collection tree: either a `Collection` with nested
+/// children, or a leaf `Field` coming from an Input/Output/Feature item.
+#[derive(Clone, Debug)]
+pub enum Node {
+    Collection {
+        usage_page: Usage,
+        usage: Usage,
+        collection_type: CollectionType,
+        children: Vec<Node>
+    },
+    Field(Field)
+}
+
+/// The result of parsing a raw HID report descriptor: the top-level
+/// collections, in the order they appeared in the byte stream.
+#[derive(Clone, Debug, Default)]
+pub struct ReportDescriptor {
+    pub collections: Vec<Node>
+}
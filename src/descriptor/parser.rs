@@ -0,0 +1,357 @@
+//! Parses a raw HID report descriptor byte stream into a [`ReportDescriptor`]
+//! tree. This walks the same short/long item encoding that
+//! `crate::windows_native::descriptor` writes out, but in reverse.
+
+use crate::descriptor::{CollectionType, Field, FieldUsage, Node, ReportDescriptor, ReportType};
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ParseError {
+    /// The byte stream ended in the middle of an item.
+    UnexpectedEndOfData,
+    /// An `End Collection` item appeared with no matching `Collection`.
+    UnmatchedEndCollection,
+    /// The stream ended with one or more `Collection` items still open.
+    UnterminatedCollection
+}
+
+// HID short item `bType` values
+const ITEM_TYPE_MAIN: u8 = 0;
+const ITEM_TYPE_GLOBAL: u8 = 1;
+const ITEM_TYPE_LOCAL: u8 = 2;
+
+// HID main item `bTag` values
+const TAG_MAIN_INPUT: u8 = 0x8;
+const TAG_MAIN_OUTPUT: u8 = 0x9;
+const TAG_MAIN_COLLECTION: u8 = 0xA;
+const TAG_MAIN_FEATURE: u8 = 0xB;
+const TAG_MAIN_END_COLLECTION: u8 = 0xC;
+
+// HID global item `bTag` values
+const TAG_GLOBAL_USAGE_PAGE: u8 = 0x0;
+const TAG_GLOBAL_LOGICAL_MIN: u8 = 0x1;
+const TAG_GLOBAL_LOGICAL_MAX: u8 = 0x2;
+const TAG_GLOBAL_PHYSICAL_MIN: u8 = 0x3;
+const TAG_GLOBAL_PHYSICAL_MAX: u8 = 0x4;
+const TAG_GLOBAL_UNIT_EXPONENT: u8 = 0x5;
+const TAG_GLOBAL_UNIT: u8 = 0x6;
+const TAG_GLOBAL_REPORT_SIZE: u8 = 0x7;
+const TAG_GLOBAL_REPORT_ID: u8 = 0x8;
+const TAG_GLOBAL_REPORT_COUNT: u8 = 0x9;
+const TAG_GLOBAL_PUSH: u8 = 0xA;
+const TAG_GLOBAL_POP: u8 = 0xB;
+
+// HID local item `bTag` values
+const TAG_LOCAL_USAGE: u8 = 0x0;
+const TAG_LOCAL_USAGE_MIN: u8 = 0x1;
+const TAG_LOCAL_USAGE_MAX: u8 = 0x2;
+
+// The long item escape prefix (HID 1.11 ยง6.2.2.3)
+const LONG_ITEM_PREFIX: u8 = 0xFE;
+
+struct Item {
+    tag: u8,
+    item_type: u8,
+    // Sign-extended to 32 bits, for fields that are actually signed
+    // (logical/physical min/max).
+    data: i32,
+    // The same bytes zero-extended instead, for fields that are unsigned
+    // (usage, report size/count/id, unit, ...) so a set top bit in a 1- or
+    // 2-byte encoding doesn't get misread as a sign.
+    raw: u32
+}
+
+/// Decodes the next short item at `*pos`, advancing `*pos` past it. Long
+/// items are skipped entirely, since nothing in the base HID spec needs
+/// their vendor-defined payload.
+fn next_item(bytes: &[u8], pos: &mut usize) -> Result<Option<Item>, ParseError> {
+    loop {
+        let Some(&prefix) = bytes.get(*pos) else {
+            return Ok(None);
+        };
+        if prefix == LONG_ITEM_PREFIX {
+            let data_size = *bytes.get(*pos + 1).ok_or(ParseError::UnexpectedEndOfData)? as usize;
+            let _long_item_tag = *bytes.get(*pos + 2).ok_or(ParseError::UnexpectedEndOfData)?;
+            let next_pos = *pos + 3 + data_size;
+            if next_pos > bytes.len() {
+                return Err(ParseError::UnexpectedEndOfData);
+            }
+            *pos = next_pos;
+            continue;
+        }
+
+        let size_code = prefix & 0x3;
+        let item_type = (prefix >> 2) & 0x3;
+        let tag = (prefix >> 4) & 0xF;
+        let data_len = match size_code {
+            0 => 0,
+            1 => 1,
+            2 => 2,
+            _ => 4
+        };
+        let data_start = *pos + 1;
+        let data_end = data_start + data_len;
+        let data_bytes = bytes.get(data_start..data_end).ok_or(ParseError::UnexpectedEndOfData)?;
+
+        let mut raw = 0u32;
+        for (i, byte) in data_bytes.iter().enumerate() {
+            raw |= (*byte as u32) << (8 * i);
+        }
+        // Sign-extend 1/2-byte values so signed global items (logical/
+        // physical min/max) come out correct; 4-byte values already fill an
+        // i32, and unsigned fields read `raw` instead.
+        let data = match data_len {
+            1 => raw as i8 as i32,
+            2 => raw as i16 as i32,
+            _ => raw as i32
+        };
+
+        *pos = data_end;
+        return Ok(Some(Item { tag, item_type, data, raw }));
+    }
+}
+
+#[derive(Clone, Default)]
+struct GlobalState {
+    usage_page: u16,
+    logical_min: i32,
+    logical_max: i32,
+    physical_min: i32,
+    physical_max: i32,
+    unit: u32,
+    unit_exponent: u32,
+    report_size: u16,
+    report_count: u16,
+    report_id: u8
+}
+
+#[derive(Default)]
+struct LocalState {
+    usage: Option<u16>,
+    usage_min: Option<u16>,
+    usage_max: Option<u16>
+}
+
+impl LocalState {
+    fn take_field_usage(&mut self) -> FieldUsage {
+        match (self.usage_min.take(), self.usage_max.take()) {
+            (Some(min), Some(max)) => FieldUsage::Range(min, max),
+            _ => FieldUsage::Single(self.usage.take().unwrap_or(0))
+        }
+    }
+}
+
+struct OpenCollection {
+    usage_page: u16,
+    usage: u16,
+    collection_type: CollectionType,
+    children: Vec<Node>
+}
+
+/// Parses a raw HID report descriptor into a [`ReportDescriptor`] tree.
+pub fn parse(bytes: &[u8]) -> Result<ReportDescriptor, ParseError> {
+    let mut pos = 0;
+    let mut globals = GlobalState::default();
+    let mut global_stack: Vec<GlobalState> = Vec::new();
+    let mut locals = LocalState::default();
+    let mut bit_offsets: std::collections::HashMap<(u8, ReportType), u32> = std::collections::HashMap::new();
+    let mut stack: Vec<OpenCollection> = Vec::new();
+    let mut top_level: Vec<Node> = Vec::new();
+
+    while let Some(item) = next_item(bytes, &mut pos)? {
+        match item.item_type {
+            ITEM_TYPE_GLOBAL => match item.tag {
+                TAG_GLOBAL_USAGE_PAGE => globals.usage_page = item.raw as u16,
+                TAG_GLOBAL_LOGICAL_MIN => globals.logical_min = item.data,
+                TAG_GLOBAL_LOGICAL_MAX => globals.logical_max = item.data,
+                TAG_GLOBAL_PHYSICAL_MIN => globals.physical_min = item.data,
+                TAG_GLOBAL_PHYSICAL_MAX => globals.physical_max = item.data,
+                TAG_GLOBAL_UNIT_EXPONENT => globals.unit_exponent = item.raw,
+                TAG_GLOBAL_UNIT => globals.unit = item.raw,
+                TAG_GLOBAL_REPORT_SIZE => globals.report_size = item.raw as u16,
+                TAG_GLOBAL_REPORT_COUNT => globals.report_count = item.raw as u16,
+                TAG_GLOBAL_REPORT_ID => globals.report_id = item.raw as u8,
+                TAG_GLOBAL_PUSH => global_stack.push(globals.clone()),
+                TAG_GLOBAL_POP => globals = global_stack.pop().unwrap_or_default(),
+                _ => {}
+            },
+            ITEM_TYPE_LOCAL => match item.tag {
+                TAG_LOCAL_USAGE => locals.usage = Some(item.raw as u16),
+                TAG_LOCAL_USAGE_MIN => locals.usage_min = Some(item.raw as u16),
+                TAG_LOCAL_USAGE_MAX => locals.usage_max = Some(item.raw as u16),
+                _ => {}
+            },
+            ITEM_TYPE_MAIN => match item.tag {
+                TAG_MAIN_COLLECTION => {
+                    stack.push(OpenCollection {
+                        usage_page: globals.usage_page,
+                        usage: locals.take_field_usage().single_or(0),
+                        collection_type: CollectionType::from_raw(item.raw as u8),
+                        children: Vec::new()
+                    });
+                    locals = LocalState::default();
+                }
+                TAG_MAIN_END_COLLECTION => {
+                    let open = stack.pop().ok_or(ParseError::UnmatchedEndCollection)?;
+                    let node = Node::Collection {
+                        usage_page: open.usage_page,
+                        usage: open.usage,
+                        collection_type: open.collection_type,
+                        children: open.children
+                    };
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(node),
+                        None => top_level.push(node)
+                    }
+                    locals = LocalState::default();
+                }
+                TAG_MAIN_INPUT | TAG_MAIN_OUTPUT | TAG_MAIN_FEATURE => {
+                    let report_type = match item.tag {
+                        TAG_MAIN_INPUT => ReportType::Input,
+                        TAG_MAIN_OUTPUT => ReportType::Output,
+                        _ => ReportType::Feature
+                    };
+                    let bit_offset = bit_offsets.entry((globals.report_id, report_type)).or_insert(0);
+                    let field = Field {
+                        report_type,
+                        report_id: globals.report_id,
+                        usage_page: globals.usage_page,
+                        usage: locals.take_field_usage(),
+                        logical_min: globals.logical_min,
+                        logical_max: globals.logical_max,
+                        physical_min: globals.physical_min,
+                        physical_max: globals.physical_max,
+                        unit: globals.unit,
+                        unit_exponent: globals.unit_exponent,
+                        report_size: globals.report_size,
+                        report_count: globals.report_count,
+                        bit_offset: *bit_offset,
+                        flags: item.raw
+                    };
+                    *bit_offset += globals.report_size as u32 * globals.report_count as u32;
+                    let node = Node::Field(field);
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(node),
+                        None => top_level.push(node)
+                    }
+                    locals = LocalState::default();
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    if !stack.is_empty() {
+        return Err(ParseError::UnterminatedCollection);
+    }
+
+    Ok(ReportDescriptor { collections: top_level })
+}
+
+impl FieldUsage {
+    fn single_or(self, default: u16) -> u16 {
+        match self {
+            FieldUsage::Single(usage) => usage,
+            FieldUsage::Range(min, _) => if min != 0 { min } else { default }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One application collection, Report ID 1, with an 8-bit Input field
+    /// followed by an 8-bit Output field sharing that report ID.
+    fn sample_descriptor() -> Vec<u8> {
+        vec![
+            0x05, 0x01, // Usage Page (1)
+            0x09, 0x02, // Usage (2)
+            0xa1, 0x01, // Collection (Application)
+            0x85, 0x01, //   Report ID (1)
+            0x75, 0x08, //   Report Size (8)
+            0x95, 0x01, //   Report Count (1)
+            0x15, 0x00, //   Logical Minimum (0)
+            0x25, 0x01, //   Logical Maximum (1)
+            0x09, 0x30, //   Usage (0x30)
+            0x81, 0x02, //   Input (Data, Variable, Absolute)
+            0x09, 0x31, //   Usage (0x31)
+            0x91, 0x02, //   Output (Data, Variable, Absolute)
+            0xc0 // End Collection
+        ]
+    }
+
+    #[test]
+    fn parses_a_hand_built_descriptor() {
+        let descriptor = parse(&sample_descriptor()).unwrap();
+        assert_eq!(descriptor.collections.len(), 1);
+        let Node::Collection { usage_page, usage, collection_type, children } = &descriptor.collections[0] else {
+            panic!("expected a Collection node");
+        };
+        assert_eq!(*usage_page, 1);
+        assert_eq!(*usage, 2);
+        assert_eq!(*collection_type, CollectionType::Application);
+        assert_eq!(children.len(), 2);
+
+        let Node::Field(input) = &children[0] else { panic!("expected a Field node") };
+        assert_eq!(input.report_type, ReportType::Input);
+        assert_eq!(input.report_id, 1);
+        assert_eq!(input.usage, FieldUsage::Single(0x30));
+        assert_eq!(input.report_size, 8);
+        assert_eq!(input.report_count, 1);
+
+        let Node::Field(output) = &children[1] else { panic!("expected a Field node") };
+        assert_eq!(output.report_type, ReportType::Output);
+        assert_eq!(output.usage, FieldUsage::Single(0x31));
+    }
+
+    #[test]
+    fn bit_offsets_are_tracked_per_report_type_not_just_report_id() {
+        // Regression test: Report ID 1 is reused by both the Input and
+        // Output fields above. Each report type has its own byte stream, so
+        // both fields must start at bit offset 0 rather than the Output
+        // field inheriting the Input field's bit offset.
+        let descriptor = parse(&sample_descriptor()).unwrap();
+        let Node::Collection { children, .. } = &descriptor.collections[0] else {
+            panic!("expected a Collection node");
+        };
+        let Node::Field(input) = &children[0] else { panic!("expected a Field node") };
+        let Node::Field(output) = &children[1] else { panic!("expected a Field node") };
+        assert_eq!(input.bit_offset, 0);
+        assert_eq!(output.bit_offset, 0);
+    }
+
+    #[test]
+    fn unmatched_end_collection_is_an_error() {
+        assert_eq!(parse(&[0xc0]).unwrap_err(), ParseError::UnmatchedEndCollection);
+    }
+
+    #[test]
+    fn unterminated_collection_is_an_error() {
+        assert_eq!(parse(&[0xa1, 0x01]).unwrap_err(), ParseError::UnterminatedCollection);
+    }
+
+    #[test]
+    fn unit_item_with_high_bit_set_is_not_sign_extended() {
+        // Regression test: a `Unit` item encoded in a single byte whose top
+        // bit is set (0xF1 = SI Linear, Length Exponent -1) must come out as
+        // the unsigned word 0x000000F1, not sign-extended to 0xFFFFFFF1.
+        let bytes = vec![
+            0xa1, 0x01, //   Collection (Application)
+            0x75, 0x08, //   Report Size (8)
+            0x95, 0x01, //   Report Count (1)
+            0x15, 0x00, //   Logical Minimum (0)
+            0x25, 0x01, //   Logical Maximum (1)
+            0x65, 0xf1, //   Unit (0xF1)
+            0x09, 0x30, //   Usage (0x30)
+            0x81, 0x02, //   Input (Data, Variable, Absolute)
+            0xc0 // End Collection
+        ];
+        let descriptor = parse(&bytes).unwrap();
+        let Node::Collection { children, .. } = &descriptor.collections[0] else {
+            panic!("expected a Collection node");
+        };
+        let Node::Field(input) = &children[0] else { panic!("expected a Field node") };
+        assert_eq!(input.unit, 0x000000F1);
+    }
+}
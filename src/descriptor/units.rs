@@ -0,0 +1,204 @@
+//! Decodes the HID `Unit`/`Unit Exponent` global items (HID 1.11 ยง6.2.2.7)
+//! into a structured physical dimension plus an SI scaling factor.
+
+use std::fmt;
+
+/// The measurement system selected by nibble 0 of the HID `Unit` item.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum System {
+    None,
+    SiLinear,
+    SiRotation,
+    EnglishLinear,
+    EnglishRotation
+}
+
+impl System {
+    fn from_nibble(nibble: u32) -> Option<Self> {
+        match nibble {
+            0 => Some(System::None),
+            1 => Some(System::SiLinear),
+            2 => Some(System::SiRotation),
+            3 => Some(System::EnglishLinear),
+            4 => Some(System::EnglishRotation),
+            _ => None
+        }
+    }
+}
+
+/// A decoded HID `Unit`/`Unit Exponent` pair: the measurement system, the
+/// per-dimension exponents (nibbles 1..=6: Length, Mass, Time, Temperature,
+/// Current, Luminous Intensity), and the base-10 scale applied to reported
+/// values.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct HidUnit {
+    pub system: System,
+    pub length_exp: i8,
+    pub mass_exp: i8,
+    pub time_exp: i8,
+    pub temperature_exp: i8,
+    pub current_exp: i8,
+    pub luminous_intensity_exp: i8,
+    /// Power-of-ten scale factor from the `Unit Exponent` item: a reported
+    /// value `v` represents `v * 10^exponent` of the unit described above.
+    pub exponent: i8
+}
+
+/// Interprets a HID unit nibble (values 8..=15 mean -8..=-1) as a signed
+/// 4-bit integer.
+fn signed_nibble(value: u32) -> i8 {
+    let nibble = (value & 0xF) as i8;
+    if nibble >= 8 {
+        nibble - 16
+    } else {
+        nibble
+    }
+}
+
+impl HidUnit {
+    /// Decodes the raw `Caps::units`/`Caps::units_exp` fields into a
+    /// structured unit. Returns `None` if nibble 0 of `units` selects an
+    /// unknown measurement system.
+    pub fn from_raw(units: u32, units_exp: u32) -> Option<HidUnit> {
+        let system = System::from_nibble(units & 0xF)?;
+        Some(HidUnit {
+            system,
+            length_exp: signed_nibble(units >> 4),
+            mass_exp: signed_nibble(units >> 8),
+            time_exp: signed_nibble(units >> 12),
+            temperature_exp: signed_nibble(units >> 16),
+            current_exp: signed_nibble(units >> 20),
+            luminous_intensity_exp: signed_nibble(units >> 24),
+            exponent: signed_nibble(units_exp)
+        })
+    }
+
+    /// The multiplier that converts a logical value carrying this unit into
+    /// the unit rendered by [`Display`](std::fmt::Display) (`10^exponent`),
+    /// e.g. with a `Unit Exponent` of -2 on a centimetre unit the multiplier
+    /// is 0.01, so a logical value of 150 stands for 1.5 cm. No conversion
+    /// from centimetre/gram to metre/kilogram is applied.
+    pub fn si_multiplier(&self) -> f64 {
+        10f64.powi(self.exponent as i32)
+    }
+
+    /// The base unit symbol and exponent for each of the six dimensions, in
+    /// the order Length, Mass, Time, Temperature, Current, Luminous
+    /// Intensity. HID's SI systems use centimetre/gram/second as their base
+    /// units rather than metre/kilogram/second.
+    fn dimensions(&self) -> [(&'static str, i8); 6] {
+        let (length, mass, time) = match self.system {
+            System::SiLinear => ("cm", "g", "s"),
+            System::SiRotation => ("rad", "g", "s"),
+            System::EnglishLinear => ("in", "slug", "s"),
+            System::EnglishRotation => ("deg", "slug", "s"),
+            System::None => ("", "", "")
+        };
+        [
+            (length, self.length_exp),
+            (mass, self.mass_exp),
+            (time, self.time_exp),
+            ("K", self.temperature_exp),
+            ("A", self.current_exp),
+            ("cd", self.luminous_intensity_exp)
+        ]
+    }
+}
+
+/// Renders a magnitude as Unicode superscript digits (e.g. `2` -> `²`), to
+/// match how HID unit symbols are conventionally typeset (`cm²`, not `cm2`).
+fn superscript(magnitude: u8) -> String {
+    magnitude
+        .to_string()
+        .chars()
+        .map(|digit| match digit {
+            '0' => '\u{2070}',
+            '1' => '\u{00b9}',
+            '2' => '\u{00b2}',
+            '3' => '\u{00b3}',
+            '4' => '\u{2074}',
+            '5' => '\u{2075}',
+            '6' => '\u{2076}',
+            '7' => '\u{2077}',
+            '8' => '\u{2078}',
+            _ => '\u{2079}'
+        })
+        .collect()
+}
+
+impl fmt::Display for HidUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut numerator = String::new();
+        let mut denominator = String::new();
+        for (symbol, exp) in self.dimensions() {
+            if symbol.is_empty() || exp == 0 {
+                continue;
+            }
+            let part = if exp > 0 { &mut numerator } else { &mut denominator };
+            if !part.is_empty() {
+                part.push('\u{b7}');
+            }
+            part.push_str(symbol);
+            let magnitude = exp.unsigned_abs();
+            if magnitude != 1 {
+                part.push_str(&superscript(magnitude));
+            }
+        }
+        match (numerator.is_empty(), denominator.is_empty()) {
+            (true, true) => Ok(()),
+            (false, true) => write!(f, "{numerator}"),
+            (true, false) => write!(f, "1/{denominator}"),
+            (false, false) => write!(f, "{numerator}/{denominator}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn centimetre() {
+        // HID 1.11 ยง6.2.2.7 example: System = SI Linear (1), Length Exponent
+        // = 1, Unit Exponent = 0.
+        let unit = HidUnit::from_raw(0x11, 0x0).unwrap();
+        assert_eq!(unit.system, System::SiLinear);
+        assert_eq!(unit.length_exp, 1);
+        assert_eq!(unit.mass_exp, 0);
+        assert_eq!(unit.exponent, 0);
+        assert_eq!(unit.to_string(), "cm");
+    }
+
+    #[test]
+    fn radian() {
+        // HID 1.11 ยง6.2.2.7 example: System = SI Rotation (2), Length
+        // (angle) Exponent = 1.
+        let unit = HidUnit::from_raw(0x12, 0x0).unwrap();
+        assert_eq!(unit.system, System::SiRotation);
+        assert_eq!(unit.length_exp, 1);
+        assert_eq!(unit.to_string(), "rad");
+    }
+
+    #[test]
+    fn gram_centimetre_squared_per_second_squared() {
+        // System = SI Linear (1), Length Exponent = 2, Mass Exponent = 1,
+        // Time Exponent = -2.
+        let unit = HidUnit::from_raw(0xe121, 0x0).unwrap();
+        assert_eq!(unit.length_exp, 2);
+        assert_eq!(unit.mass_exp, 1);
+        assert_eq!(unit.time_exp, -2);
+        assert_eq!(unit.to_string(), "cm\u{b2}\u{b7}g/s\u{b2}");
+    }
+
+    #[test]
+    fn unit_exponent_scales_si_multiplier() {
+        let unit = HidUnit::from_raw(0x11, 0xe).unwrap();
+        assert_eq!(unit.exponent, -2);
+        assert!((unit.si_multiplier() - 0.01).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn unknown_system_is_none() {
+        assert!(HidUnit::from_raw(0xf, 0x0).is_none());
+    }
+}
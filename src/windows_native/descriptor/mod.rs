@@ -6,7 +6,8 @@ use std::iter::once;
 use std::ptr::addr_of;
 use std::slice;
 use crate::ensure;
-use crate::windows_native::descriptor::typedefs::{HidpPreparsedData, LinkCollectionNode, ReportType};
+use crate::descriptor::FieldUsage;
+use crate::windows_native::descriptor::typedefs::{Caps, HidpPreparsedData, LinkCollectionNode, ReportType, Usage};
 use crate::windows_native::error::{WinError, WinResult};
 use crate::windows_native::hid::PreparsedData;
 
@@ -16,186 +17,623 @@ struct BitRange {
     last_bit: Option<u16>
 }
 
-const INVALID_DATA: WinResult<usize> = Err(WinError::InvalidPreparsedData);
+/// Index into the flat `coll_bit_range` table: `[COLLECTION_INDEX][REPORT_ID][INPUT/OUTPUT/FEATURE]`.
+fn bit_range_index(collection_idx: u16, report_id: u16, report_type: ReportType) -> usize {
+    collection_idx as usize * 256 * 3 + report_id as usize * 3 + report_type as usize
+}
+
+/// Index into the flat `coll_child_order` table: `[COLLECTION_INDEX][DIRECT_CHILD_INDEX]`.
+/// The number of direct children of any collection is bounded by the total
+/// number of collections, so that bound is used as the row stride.
+fn child_order_index(num_collections: usize, collection_idx: u16, child_idx: u16) -> usize {
+    collection_idx as usize * num_collections + child_idx as usize
+}
+
+/// Validates the magic key of `pp_data` and returns the header pointer
+/// together with its link collection node slice. Shared by `get_descriptor`
+/// and the safe accessors below so the unsafe pointer arithmetic lives in
+/// one place.
+unsafe fn header_and_nodes(pp_data: &PreparsedData) -> WinResult<(*const HidpPreparsedData, &[LinkCollectionNode])> {
+    let header: *const HidpPreparsedData = pp_data.as_ptr() as _;
+    // Check if MagicKey is correct, to ensure that pp_data points to an valid preparse data structure
+    ensure!(&(*header).magic_key == b"HidP KDR", Err(WinError::InvalidPreparsedData));
+    // Set pointer to the first node of link_collection_nodes
+    let link_collection_nodes = {
+        let ptr: *const LinkCollectionNode = ((addr_of!((*header).caps_info[0]) as *const c_void).offset((*header).first_byte_of_link_collection_array as isize)) as _;
+        let len = (*header).number_link_collection_nodes as usize;
+        slice::from_raw_parts(ptr, len)
+    };
+    Ok((header, link_collection_nodes))
+}
+
+/// Reads the cap at `idx` from the header's trailing, variable-length caps
+/// array. The declared `caps: [Caps; 1]` field only gives bounds-checked
+/// access to index 0 (any higher index panics, since Rust array indexing is
+/// checked against the declared length regardless of what's physically
+/// adjacent in memory); every other cap has to be read through raw pointer
+/// arithmetic off that same address instead.
+unsafe fn caps_at(header: *const HidpPreparsedData, idx: u16) -> Caps {
+    *addr_of!((*header).caps[0]).add(idx as usize)
+}
 
 pub fn get_descriptor(pp_data: &PreparsedData, buf: &mut [u8]) -> WinResult<usize> {
-    //let mut out = buf;
     unsafe {
-        let header: *const HidpPreparsedData = pp_data.as_ptr() as _;
-        // Check if MagicKey is correct, to ensure that pp_data points to an valid preparse data structure
-        ensure!(&(*header).magic_key == b"HidP KDR", INVALID_DATA);
-        // Set pointer to the first node of link_collection_nodes
-        let link_collection_nodes = {
-            let ptr: *const LinkCollectionNode = ((addr_of!((*header).caps_info[0]) as *const c_void).offset((*header).first_byte_of_link_collection_array as isize)) as _;
-            let len = (*header).number_link_collection_nodes as usize;
-            slice::from_raw_parts(ptr, len)
-        };
+        let (header, link_collection_nodes) = header_and_nodes(pp_data)?;
+        Ok(get_descriptor_from_header(header, link_collection_nodes, buf))
+    }
+}
 
-        // ****************************************************************************************************************************
-        // Create lookup tables for the bit range of each report per collection (position of first bit and last bit in each collection)
-        // coll_bit_range[COLLECTION_INDEX][REPORT_ID][INPUT/OUTPUT/FEATURE]
-        // ****************************************************************************************************************************
-        let mut coll_bit_range: HashMap<(u16, u16, ReportType), BitRange> = HashMap::new();
-        for collection_node_idx in 0..((*header).number_link_collection_nodes) {
-            for reportid_idx in 0..256 {
-                for rt_idx in ReportType::values() {
-                    coll_bit_range.insert((collection_node_idx, reportid_idx, rt_idx), BitRange::default());
-                }
-            }
-        }
+/// Does the actual work of `get_descriptor` once the preparsed-data header
+/// and its link collection node slice have been carved out of `pp_data`.
+/// Split out so tests can exercise it directly against a hand-built buffer,
+/// without going through a live Windows preparsed data handle.
+unsafe fn get_descriptor_from_header(header: *const HidpPreparsedData, link_collection_nodes: &[LinkCollectionNode], buf: &mut [u8]) -> usize {
+    // ****************************************************************************************************************************
+    // Create lookup tables for the bit range of each report per collection (position of first bit and last bit in each collection)
+    // coll_bit_range[COLLECTION_INDEX][REPORT_ID][INPUT/OUTPUT/FEATURE]
+    // ****************************************************************************************************************************
+    let num_collections = (*header).number_link_collection_nodes as usize;
+    let mut coll_bit_range: Vec<BitRange> = vec![BitRange::default(); num_collections * 256 * 3];
 
-        for rt_idx in ReportType::values() {
-            let caps_info = (*header).caps_info[rt_idx as usize];
-            for caps_idx in caps_info.first_cap..caps_info.last_cap {
-                let caps = (*header).caps[caps_idx as usize];
-                let first_bit = (caps.byte_position - 1) * 8 + caps.bit_position as u16;
-                let last_bit = first_bit + caps.report_size * caps.report_count - 1;
-                let range = coll_bit_range.get_mut(&(caps.link_collection, caps.report_id as u16, rt_idx)).unwrap();
-                range.first_bit = range.first_bit.into_iter().chain(once(first_bit)).min();
-                range.last_bit = range.last_bit.into_iter().chain(once(last_bit)).max();
-            }
+    for rt_idx in ReportType::values() {
+        let caps_info = (*header).caps_info[rt_idx as usize];
+        for caps_idx in caps_info.first_cap..caps_info.last_cap {
+            let caps = caps_at(header, caps_idx);
+            let first_bit = (caps.byte_position - 1) * 8 + caps.bit_position as u16;
+            let last_bit = first_bit + caps.report_size * caps.report_count - 1;
+            let range = &mut coll_bit_range[bit_range_index(caps.link_collection, caps.report_id as u16, rt_idx)];
+            range.first_bit = range.first_bit.into_iter().chain(once(first_bit)).min();
+            range.last_bit = range.last_bit.into_iter().chain(once(last_bit)).max();
         }
+    }
 
-        // *************************************************************************
-        // -Determine hierachy levels of each collections and store it in:
-        //  coll_levels[COLLECTION_INDEX]
-        // -Determine number of direct childs of each collections and store it in:
-        //  coll_number_of_direct_childs[COLLECTION_INDEX]
-        // *************************************************************************
-        let mut max_coll_level = 0;
-        let mut coll_levels = Vec::new();
-        let mut coll_number_of_direct_childs = Vec::new();
-        for _ in 0..((*header).number_link_collection_nodes) {
-            coll_levels.push(-1);
-            coll_number_of_direct_childs.push(0);
-        }
+    // *************************************************************************
+    // -Determine hierachy levels of each collections and store it in:
+    //  coll_levels[COLLECTION_INDEX]
+    // -Determine number of direct childs of each collections and store it in:
+    //  coll_number_of_direct_childs[COLLECTION_INDEX]
+    // *************************************************************************
+    let mut max_coll_level = 0;
+    let mut coll_levels = Vec::new();
+    let mut coll_number_of_direct_childs = Vec::new();
+    for _ in 0..((*header).number_link_collection_nodes) {
+        coll_levels.push(-1);
+        coll_number_of_direct_childs.push(0);
+    }
 
-        {
-            let mut actual_coll_level = 0;
-            let mut collection_node_idx = 0;
-            while actual_coll_level >= 0 {
+    {
+        let mut actual_coll_level = 0;
+        let mut collection_node_idx = 0;
+        while actual_coll_level >= 0 {
+            coll_levels[collection_node_idx] = actual_coll_level;
+            let node = link_collection_nodes[collection_node_idx];
+            if node.number_of_children > 0 && coll_levels[node.first_child as usize] == -1 {
+                actual_coll_level += 1;
                 coll_levels[collection_node_idx] = actual_coll_level;
-                let node = link_collection_nodes[collection_node_idx];
-                if node.number_of_children > 0 && coll_levels[node.first_child as usize] == -1 {
-                    actual_coll_level += 1;
-                    coll_levels[collection_node_idx] = actual_coll_level;
-                    max_coll_level = max_coll_level.max(actual_coll_level);
-                    coll_number_of_direct_childs[collection_node_idx] += 1;
-                    collection_node_idx = node.first_child as usize;
-                } else if node.next_sibling != 0 {
-                    coll_number_of_direct_childs[node.parent as usize] += 1;
-                    collection_node_idx = node.next_sibling as usize;
-                } else {
-                    actual_coll_level -= 1;
-                    if actual_coll_level >= 0 {
-                        collection_node_idx = node.parent as usize;
-                    }
+                max_coll_level = max_coll_level.max(actual_coll_level);
+                coll_number_of_direct_childs[collection_node_idx] += 1;
+                collection_node_idx = node.first_child as usize;
+            } else if node.next_sibling != 0 {
+                coll_number_of_direct_childs[node.parent as usize] += 1;
+                collection_node_idx = node.next_sibling as usize;
+            } else {
+                actual_coll_level -= 1;
+                if actual_coll_level >= 0 {
+                    collection_node_idx = node.parent as usize;
                 }
             }
         }
+    }
 
-        // *********************************************************************************
-        // Propagate the bit range of each report from the child collections to their parent
-        // and store the merged result for the parent
-        // *********************************************************************************
-        for actual_coll_level in (0..max_coll_level).rev() {
-            for collection_node_idx in 0..link_collection_nodes.len() {
-                if coll_levels[collection_node_idx] == actual_coll_level {
-                    let mut child_idx = link_collection_nodes[collection_node_idx].first_child;
-                    while child_idx != 0 {
-                        for reportid_idx in 0..256 {
-                            for rt_idx in ReportType::values() {
-                                let child = coll_bit_range
-                                    .get(&(child_idx, reportid_idx, rt_idx))
-                                    .unwrap()
-                                    .clone();
-                                let parent = coll_bit_range
-                                    .get_mut(&(collection_node_idx as u16, reportid_idx, rt_idx))
-                                    .unwrap();
-                                parent.first_bit = parent.first_bit.into_iter().chain(child.first_bit).min();
-                                parent.last_bit = parent.last_bit.into_iter().chain(child.last_bit).max();
-                                child_idx = link_collection_nodes[child_idx as usize].next_sibling;
-                            }
+    // *********************************************************************************
+    // Propagate the bit range of each report from the child collections to their parent
+    // and store the merged result for the parent
+    // *********************************************************************************
+    for actual_coll_level in (0..max_coll_level).rev() {
+        for collection_node_idx in 0..link_collection_nodes.len() {
+            if coll_levels[collection_node_idx] == actual_coll_level {
+                let mut child_idx = link_collection_nodes[collection_node_idx].first_child;
+                while child_idx != 0 {
+                    for reportid_idx in 0..256 {
+                        for rt_idx in ReportType::values() {
+                            let child = coll_bit_range[bit_range_index(child_idx, reportid_idx, rt_idx)];
+                            let parent = &mut coll_bit_range[bit_range_index(collection_node_idx as u16, reportid_idx, rt_idx)];
+                            parent.first_bit = parent.first_bit.into_iter().chain(child.first_bit).min();
+                            parent.last_bit = parent.last_bit.into_iter().chain(child.last_bit).max();
                         }
                     }
+                    child_idx = link_collection_nodes[child_idx as usize].next_sibling;
                 }
             }
         }
+    }
 
-        // *************************************************************************************************
-        // Determine child collection order of the whole hierachy, based on previously determined bit ranges
-        // and store it this index coll_child_order[COLLECTION_INDEX][DIRECT_CHILD_INDEX]
-        // *************************************************************************************************
-        let mut coll_child_order: HashMap<(u16, u16), u16> = HashMap::new();
-        {
-            let mut coll_parsed_flag = vec![false; link_collection_nodes.len()];
-            let mut actual_coll_level = 0;
-            let mut collection_node_idx = 0;
-            while actual_coll_level >= 0 {
-                if coll_number_of_direct_childs[collection_node_idx] != 0 &&
-                    !coll_parsed_flag[link_collection_nodes[collection_node_idx].first_child as usize] {
-                    coll_parsed_flag[link_collection_nodes[collection_node_idx].first_child as usize] = true;
-
-                    {
-                        // Create list of child collection indices
-                        // sorted reverse to the order returned to HidP_GetLinkCollectionNodeschild
-                        // which seems to match teh original order, as long as no bit position needs to be considered
-                        let mut child_idx = link_collection_nodes[collection_node_idx].first_child;
-                        let mut child_count = coll_number_of_direct_childs[collection_node_idx] - 1;
-                        coll_child_order.insert((collection_node_idx as u16, child_count as u16), child_idx);
-                        while link_collection_nodes[child_idx as usize].next_sibling != 0 {
-                            child_count -= 1;
-                            child_idx = link_collection_nodes[child_idx as usize].next_sibling;
-                            coll_child_order.insert((collection_node_idx as u16, child_count as u16), child_idx);
-                        }
+    // *************************************************************************************************
+    // Determine child collection order of the whole hierachy, based on previously determined bit ranges
+    // and store it this index coll_child_order[COLLECTION_INDEX][DIRECT_CHILD_INDEX]
+    // *************************************************************************************************
+    let mut coll_child_order: Vec<u16> = vec![0; num_collections * num_collections];
+    {
+        let mut coll_parsed_flag = vec![false; link_collection_nodes.len()];
+        let mut actual_coll_level = 0;
+        let mut collection_node_idx = 0;
+        while actual_coll_level >= 0 {
+            if coll_number_of_direct_childs[collection_node_idx] != 0 &&
+                !coll_parsed_flag[link_collection_nodes[collection_node_idx].first_child as usize] {
+                coll_parsed_flag[link_collection_nodes[collection_node_idx].first_child as usize] = true;
+
+                {
+                    // Create list of child collection indices
+                    // sorted reverse to the order returned to HidP_GetLinkCollectionNodeschild
+                    // which seems to match teh original order, as long as no bit position needs to be considered
+                    let mut child_idx = link_collection_nodes[collection_node_idx].first_child;
+                    let mut child_count = coll_number_of_direct_childs[collection_node_idx] - 1;
+                    coll_child_order[child_order_index(num_collections, collection_node_idx as u16, child_count)] = child_idx;
+                    while link_collection_nodes[child_idx as usize].next_sibling != 0 {
+                        child_count -= 1;
+                        child_idx = link_collection_nodes[child_idx as usize].next_sibling;
+                        coll_child_order[child_order_index(num_collections, collection_node_idx as u16, child_count)] = child_idx;
                     }
+                }
 
-                    if coll_number_of_direct_childs[collection_node_idx] > 1 {
-                        // Sort child collections indices by bit positions
-                        for rt_idx in ReportType::values() {
-                            for report_idx in 0..256 {
-                                for child_idx in 1..coll_number_of_direct_childs[collection_node_idx] {
-                                    // since the coll_bit_range array is not sorted, we need to reference the collection index in
-                                    // our sorted coll_child_order array, and look up the corresponding bit ranges for comparing values to sort
-                                    let prev_coll_idx = *coll_child_order
-                                        .get(&(collection_node_idx as u16, (child_idx - 1) as u16))
-                                        .unwrap();
-                                    let cur_coll_idx = *coll_child_order
-                                        .get(&(collection_node_idx as u16, child_idx as u16))
-                                        .unwrap();
-                                    let swap = coll_bit_range
-                                        .get(&(prev_coll_idx, report_idx, rt_idx))
-                                        .and_then(|prev| prev.first_bit)
-                                        .zip(coll_bit_range
-                                            .get(&(cur_coll_idx, report_idx, rt_idx))
-                                            .and_then(|prev| prev.first_bit))
-                                        .map_or(false, |(prev, cur)| prev > cur);
-                                    if swap {
-                                        coll_child_order.insert((collection_node_idx as u16, (child_idx - 1) as u16), cur_coll_idx);
-                                        coll_child_order.insert((collection_node_idx as u16, child_idx as u16), prev_coll_idx);
-                                    }
+                if coll_number_of_direct_childs[collection_node_idx] > 1 {
+                    // Sort child collections indices by bit positions
+                    for rt_idx in ReportType::values() {
+                        for report_idx in 0..256 {
+                            for child_idx in 1..coll_number_of_direct_childs[collection_node_idx] {
+                                // since the coll_bit_range array is not sorted, we need to reference the collection index in
+                                // our sorted coll_child_order array, and look up the corresponding bit ranges for comparing values to sort
+                                let prev_slot = child_order_index(num_collections, collection_node_idx as u16, child_idx - 1);
+                                let cur_slot = child_order_index(num_collections, collection_node_idx as u16, child_idx);
+                                let prev_coll_idx = coll_child_order[prev_slot];
+                                let cur_coll_idx = coll_child_order[cur_slot];
+                                let swap = coll_bit_range[bit_range_index(prev_coll_idx, report_idx, rt_idx)].first_bit
+                                    .zip(coll_bit_range[bit_range_index(cur_coll_idx, report_idx, rt_idx)].first_bit)
+                                    .is_some_and(|(prev, cur)| prev > cur);
+                                if swap {
+                                    coll_child_order[prev_slot] = cur_coll_idx;
+                                    coll_child_order[cur_slot] = prev_coll_idx;
                                 }
                             }
                         }
                     }
-                    actual_coll_level += 1;
-                    collection_node_idx = link_collection_nodes[collection_node_idx].first_child as usize;
-                } else if link_collection_nodes[collection_node_idx].next_sibling != 0 {
-                    collection_node_idx = link_collection_nodes[collection_node_idx].next_sibling as usize;
-                } else {
-                    actual_coll_level -= 1;
-                    if actual_coll_level >= 0 {
-                        collection_node_idx = link_collection_nodes[collection_node_idx].parent as usize;
-                    }
+                }
+                actual_coll_level += 1;
+                collection_node_idx = link_collection_nodes[collection_node_idx].first_child as usize;
+            } else if link_collection_nodes[collection_node_idx].next_sibling != 0 {
+                collection_node_idx = link_collection_nodes[collection_node_idx].next_sibling as usize;
+            } else {
+                actual_coll_level -= 1;
+                if actual_coll_level >= 0 {
+                    collection_node_idx = link_collection_nodes[collection_node_idx].parent as usize;
                 }
             }
         }
+    }
+
+
+    // *******************************************************************
+    // Group the caps of every report type by the collection that owns
+    // them, sorted by bit position, so the tree walk below can emit them
+    // interleaved with the Collection/End Collection main items
+    // *******************************************************************
+    let mut coll_caps: Vec<Vec<(ReportType, Caps)>> = vec![Vec::new(); link_collection_nodes.len()];
+    for rt_idx in ReportType::values() {
+        let caps_info = (*header).caps_info[rt_idx as usize];
+        for caps_idx in caps_info.first_cap..caps_info.last_cap {
+            let caps = caps_at(header, caps_idx);
+            coll_caps[caps.link_collection as usize].push((rt_idx, caps));
+        }
+    }
+    for caps in coll_caps.iter_mut() {
+        caps.sort_by_key(|(_, caps)| (caps.byte_position - 1) as u32 * 8 + caps.bit_position as u32);
+    }
+
+    // *******************************************************************
+    // Walk the collection tree depth-first, in the child order computed
+    // above, emitting the short items that reconstruct the descriptor
+    // *******************************************************************
+    let mut writer = DescriptorWriter::new(buf);
+    let mut globals = GlobalItemState::default();
+    let mut report_last_bit: HashMap<(u8, ReportType), u16> = HashMap::new();
+    let tree = CollectionTree {
+        nodes: link_collection_nodes,
+        caps: &coll_caps,
+        number_of_direct_childs: &coll_number_of_direct_childs,
+        child_order: &coll_child_order,
+        num_collections
+    };
+    write_collection(0, &tree, &mut writer, &mut globals, &mut report_last_bit);
+    writer.len
+}
+
+// HID short item `bType` values
+const ITEM_TYPE_MAIN: u8 = 0;
+const ITEM_TYPE_GLOBAL: u8 = 1;
+const ITEM_TYPE_LOCAL: u8 = 2;
+
+// HID main item `bTag` values
+const TAG_MAIN_INPUT: u8 = 0x8;
+const TAG_MAIN_OUTPUT: u8 = 0x9;
+const TAG_MAIN_COLLECTION: u8 = 0xA;
+const TAG_MAIN_FEATURE: u8 = 0xB;
+const TAG_MAIN_END_COLLECTION: u8 = 0xC;
+
+// HID global item `bTag` values
+const TAG_GLOBAL_USAGE_PAGE: u8 = 0x0;
+const TAG_GLOBAL_LOGICAL_MIN: u8 = 0x1;
+const TAG_GLOBAL_LOGICAL_MAX: u8 = 0x2;
+const TAG_GLOBAL_PHYSICAL_MIN: u8 = 0x3;
+const TAG_GLOBAL_PHYSICAL_MAX: u8 = 0x4;
+const TAG_GLOBAL_UNIT_EXPONENT: u8 = 0x5;
+const TAG_GLOBAL_UNIT: u8 = 0x6;
+const TAG_GLOBAL_REPORT_SIZE: u8 = 0x7;
+const TAG_GLOBAL_REPORT_ID: u8 = 0x8;
+const TAG_GLOBAL_REPORT_COUNT: u8 = 0x9;
+
+// HID local item `bTag` values
+const TAG_LOCAL_USAGE: u8 = 0x0;
+const TAG_LOCAL_USAGE_MIN: u8 = 0x1;
+const TAG_LOCAL_USAGE_MAX: u8 = 0x2;
+
+// Data/Constant bit of a main item's bit field (see HID 1.11 ยง6.2.2.5)
+const MAIN_ITEM_CONSTANT: u32 = 1 << 0;
+
+/// Appends HID short items into a caller supplied buffer, tracking the total
+/// length even once the buffer is exhausted so the required size can still be
+/// reported back, mirroring the `HidP_Get*` Windows APIs.
+struct DescriptorWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize
+}
+
+impl<'a> DescriptorWriter<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    fn push(&mut self, byte: u8) {
+        if let Some(slot) = self.buf.get_mut(self.len) {
+            *slot = byte;
+        }
+        self.len += 1;
+    }
+
+    /// Appends a short item, picking the smallest of the four allowed data
+    /// sizes (0/1/2/4 bytes) that can hold `data`. Logical/physical min/max
+    /// are sized as signed values, every other item as unsigned.
+    fn write_item(&mut self, tag: u8, item_type: u8, data: i32) {
+        let signed = item_type == ITEM_TYPE_GLOBAL
+            && matches!(tag, TAG_GLOBAL_LOGICAL_MIN | TAG_GLOBAL_LOGICAL_MAX | TAG_GLOBAL_PHYSICAL_MIN | TAG_GLOBAL_PHYSICAL_MAX);
+        let (size_code, data_len) = if data == 0 {
+            (0, 0)
+        } else if (signed && (i8::MIN as i32..=i8::MAX as i32).contains(&data)) || (!signed && (0..=u8::MAX as i32).contains(&data)) {
+            (1, 1)
+        } else if (signed && (i16::MIN as i32..=i16::MAX as i32).contains(&data)) || (!signed && (0..=u16::MAX as i32).contains(&data)) {
+            (2, 2)
+        } else {
+            (3, 4)
+        };
+        self.push((tag << 4) | (item_type << 2) | size_code);
+        for byte in &data.to_le_bytes()[..data_len] {
+            self.push(*byte);
+        }
+    }
+}
+
+#[derive(Default)]
+struct GlobalItemState {
+    usage_page: Option<i32>,
+    logical_min: Option<i32>,
+    logical_max: Option<i32>,
+    physical_min: Option<i32>,
+    physical_max: Option<i32>,
+    unit: Option<i32>,
+    unit_exponent: Option<i32>,
+    report_size: Option<i32>,
+    report_count: Option<i32>,
+    report_id: Option<i32>
+}
+
+fn write_global(writer: &mut DescriptorWriter, field: &mut Option<i32>, tag: u8, value: i32) {
+    if *field != Some(value) {
+        writer.write_item(tag, ITEM_TYPE_GLOBAL, value);
+        *field = Some(value);
+    }
+}
+
+fn report_type_tag(report_type: ReportType) -> u8 {
+    match report_type {
+        ReportType::Input => TAG_MAIN_INPUT,
+        ReportType::Output => TAG_MAIN_OUTPUT,
+        ReportType::Feature => TAG_MAIN_FEATURE
+    }
+}
+
+/// Emits the Global/Local/Main items for a single cap, padding with a
+/// constant field first if a gap opened up since the previous cap on the
+/// same report id/report type.
+fn write_cap(
+    writer: &mut DescriptorWriter,
+    globals: &mut GlobalItemState,
+    report_type: ReportType,
+    caps: &Caps,
+    report_last_bit: &mut HashMap<(u8, ReportType), u16>
+) {
+    let first_bit = (caps.byte_position - 1) as u32 * 8 + caps.bit_position as u32;
+    let last_bit = first_bit + caps.report_size as u32 * caps.report_count as u32 - 1;
+
+    let key = (caps.report_id, report_type);
+    // Switch to this cap's report id first, so any padding lands in it too.
+    if caps.report_id != 0 {
+        write_global(writer, &mut globals.report_id, TAG_GLOBAL_REPORT_ID, caps.report_id as i32);
+    }
+    if let Some(&prev_last_bit) = report_last_bit.get(&key) {
+        if first_bit > prev_last_bit as u32 + 1 {
+            let gap = first_bit - prev_last_bit as u32 - 1;
+            write_global(writer, &mut globals.report_size, TAG_GLOBAL_REPORT_SIZE, gap as i32);
+            write_global(writer, &mut globals.report_count, TAG_GLOBAL_REPORT_COUNT, 1);
+            writer.write_item(report_type_tag(report_type), ITEM_TYPE_MAIN, MAIN_ITEM_CONSTANT as i32);
+        }
+    }
+    report_last_bit.insert(key, last_bit as u16);
+
+    write_global(writer, &mut globals.usage_page, TAG_GLOBAL_USAGE_PAGE, caps.usage_page as i32);
+
+    let (logical_min, logical_max, physical_min, physical_max, report_size) = if caps.is_button_cap() {
+        (0, 1, 0, 0, 1)
+    } else {
+        let not_button = unsafe { caps.maybe_button.not_button };
+        (not_button.logical_min, not_button.logical_max, not_button.physical_min, not_button.physical_max, caps.report_size as i32)
+    };
+    write_global(writer, &mut globals.logical_min, TAG_GLOBAL_LOGICAL_MIN, logical_min);
+    write_global(writer, &mut globals.logical_max, TAG_GLOBAL_LOGICAL_MAX, logical_max);
+    write_global(writer, &mut globals.physical_min, TAG_GLOBAL_PHYSICAL_MIN, physical_min);
+    write_global(writer, &mut globals.physical_max, TAG_GLOBAL_PHYSICAL_MAX, physical_max);
+    write_global(writer, &mut globals.unit, TAG_GLOBAL_UNIT, caps.units as i32);
+    write_global(writer, &mut globals.unit_exponent, TAG_GLOBAL_UNIT_EXPONENT, caps.units_exp as i32);
+    write_global(writer, &mut globals.report_size, TAG_GLOBAL_REPORT_SIZE, report_size);
+    write_global(writer, &mut globals.report_count, TAG_GLOBAL_REPORT_COUNT, caps.report_count as i32);
+
+    if caps.is_range() {
+        let range = unsafe { caps.maybe_range.range };
+        writer.write_item(TAG_LOCAL_USAGE_MIN, ITEM_TYPE_LOCAL, range.usage_min as i32);
+        writer.write_item(TAG_LOCAL_USAGE_MAX, ITEM_TYPE_LOCAL, range.usage_max as i32);
+    } else {
+        let not_range = unsafe { caps.maybe_range.not_range };
+        writer.write_item(TAG_LOCAL_USAGE, ITEM_TYPE_LOCAL, not_range.usage as i32);
+    }
 
+    writer.write_item(report_type_tag(report_type), ITEM_TYPE_MAIN, caps.bit_field as i32);
+}
+
+/// The flat, already-computed tables `write_collection` walks: the link
+/// collection nodes themselves, their caps grouped by owning collection, and
+/// the child count/order tables built further up in `get_descriptor`.
+struct CollectionTree<'a> {
+    nodes: &'a [LinkCollectionNode],
+    caps: &'a [Vec<(ReportType, Caps)>],
+    number_of_direct_childs: &'a [u16],
+    child_order: &'a [u16],
+    num_collections: usize
+}
+
+/// Recursively emits a Collection item, the caps owned directly by it, its
+/// children (in the previously computed child order) and the matching End
+/// Collection item.
+fn write_collection(
+    node_idx: u16,
+    tree: &CollectionTree,
+    writer: &mut DescriptorWriter,
+    globals: &mut GlobalItemState,
+    report_last_bit: &mut HashMap<(u8, ReportType), u16>
+) {
+    let node = tree.nodes[node_idx as usize];
+    write_global(writer, &mut globals.usage_page, TAG_GLOBAL_USAGE_PAGE, node.link_usage_page as i32);
+    writer.write_item(TAG_LOCAL_USAGE, ITEM_TYPE_LOCAL, node.link_usage as i32);
+    writer.write_item(TAG_MAIN_COLLECTION, ITEM_TYPE_MAIN, node.collection_type() as i32);
+
+    for (report_type, caps) in &tree.caps[node_idx as usize] {
+        write_cap(writer, globals, *report_type, caps, report_last_bit);
+    }
+
+    for child_slot in 0..tree.number_of_direct_childs[node_idx as usize] {
+        let child_idx = tree.child_order[child_order_index(tree.num_collections, node_idx, child_slot)];
+        write_collection(child_idx, tree, writer, globals, report_last_bit);
+    }
+
+    writer.write_item(TAG_MAIN_END_COLLECTION, ITEM_TYPE_MAIN, 0);
+}
+
+/// A safe view of one `LinkCollectionNode`, indexed the same way the
+/// `parent`/`first_child`/`next_sibling` fields of its siblings refer to it.
+/// Safe equivalent of the fields returned by Windows' `HidP_GetLinkCollectionNodes`.
+#[derive(Copy, Clone, Debug)]
+pub struct LinkCollectionInfo {
+    pub usage_page: Usage,
+    pub usage: Usage,
+    pub collection_type: u8,
+    pub is_alias: bool,
+    pub parent: u16,
+    pub number_of_children: u16,
+    pub first_child: u16,
+    pub next_sibling: u16
+}
+
+/// A safe view of one button or value cap. Safe equivalent of the fields
+/// returned by Windows' `HidP_GetButtonCaps`/`HidP_GetValueCaps`.
+#[derive(Clone, Debug)]
+pub struct CapInfo {
+    pub report_id: u8,
+    pub link_collection: u16,
+    pub usage_page: Usage,
+    pub usage: FieldUsage,
+    pub report_size: u16,
+    pub report_count: u16,
+    pub is_button: bool,
+    pub logical_min: i32,
+    pub logical_max: i32,
+    /// `None` for button caps, which carry no physical range of their own.
+    pub physical_range: Option<(i32, i32)>
+}
 
+fn resolve_usage(caps: &Caps) -> FieldUsage {
+    if caps.is_range() {
+        let range = unsafe { caps.maybe_range.range };
+        FieldUsage::Range(range.usage_min, range.usage_max)
+    } else {
+        let not_range = unsafe { caps.maybe_range.not_range };
+        FieldUsage::Single(not_range.usage)
+    }
+}
+
+fn resolve_ranges(caps: &Caps) -> (i32, i32, Option<(i32, i32)>) {
+    if caps.is_button_cap() {
+        let button = unsafe { caps.maybe_button.button };
+        (button.logical_min, button.logical_max, None)
+    } else {
+        let not_button = unsafe { caps.maybe_button.not_button };
+        (not_button.logical_min, not_button.logical_max, Some((not_button.physical_min, not_button.physical_max)))
+    }
+}
+
+/// Safe equivalent of Windows' `HidP_GetLinkCollectionNodes`: every link
+/// collection node in the preparsed data.
+pub fn link_collection_nodes(pp_data: &PreparsedData) -> WinResult<Vec<LinkCollectionInfo>> {
+    unsafe {
+        let (_, nodes) = header_and_nodes(pp_data)?;
+        Ok(nodes
+            .iter()
+            .map(|node| LinkCollectionInfo {
+                usage_page: node.link_usage_page,
+                usage: node.link_usage,
+                collection_type: node.collection_type(),
+                is_alias: node.is_alias(),
+                parent: node.parent,
+                number_of_children: node.number_of_children,
+                first_child: node.first_child,
+                next_sibling: node.next_sibling
+            })
+            .collect())
+    }
+}
+
+/// Safe equivalent of Windows' `HidP_GetButtonCaps`/`HidP_GetValueCaps`: all
+/// the caps of the given report type.
+pub fn caps(pp_data: &PreparsedData, report_type: ReportType) -> WinResult<Vec<CapInfo>> {
+    unsafe {
+        let (header, _) = header_and_nodes(pp_data)?;
+        let caps_info = (*header).caps_info[report_type as usize];
+        // Mirror the `first_cap..last_cap` range used everywhere else in this
+        // file: empty (rather than panicking or wrapping) if malformed
+        // preparsed data has `first_cap > last_cap`.
+        let count = caps_info.last_cap.saturating_sub(caps_info.first_cap) as usize;
+        let ptr: *const Caps = addr_of!((*header).caps[0]);
+        let caps = slice::from_raw_parts(ptr.add(caps_info.first_cap as usize), count);
+        Ok(caps
+            .iter()
+            .map(|caps| {
+                let (logical_min, logical_max, physical_range) = resolve_ranges(caps);
+                CapInfo {
+                    report_id: caps.report_id,
+                    link_collection: caps.link_collection,
+                    usage_page: caps.usage_page,
+                    usage: resolve_usage(caps),
+                    report_size: caps.report_size,
+                    report_count: caps.report_count,
+                    is_button: caps.is_button_cap(),
+                    logical_min,
+                    logical_max,
+                    physical_range
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::parser;
+    use crate::descriptor::Node;
+    use crate::windows_native::descriptor::typedefs::CapsInfo;
+
+    fn node(parent: u16, number_of_children: u16, next_sibling: u16, first_child: u16, collection_type: u8) -> LinkCollectionNode {
+        LinkCollectionNode {
+            link_usage: 0x30,
+            link_usage_page: 1,
+            parent,
+            number_of_children,
+            next_sibling,
+            first_child,
+            bits: collection_type as u32
+        }
+    }
+
+    #[repr(C)]
+    struct RawBuffer {
+        header: HidpPreparsedData,
+        extra_caps: [Caps; 1]
+    }
+
+    /// Two siblings (A, B), each with one grandchild that owns the collection's
+    /// only field. A1 (A's child) sits at a higher bit position than B1 (B's
+    /// child), and A comes before B in the child order built from the
+    /// sibling links (which is read in reverse: the root's first child is B,
+    /// whose next sibling is A), so only a correct propagation of each
+    /// grandchild's bit range up to its parent collection can make the sibling
+    /// sort put B before A.
+    fn two_level_composite_descriptor() -> (RawBuffer, [LinkCollectionNode; 5]) {
+        let nodes = [
+            node(0, 2, 0, 2, 1), // 0: root (Application), first_child = B
+            node(0, 1, 0, 3, 0), // 1: A (Physical), first_child = A1
+            node(0, 1, 1, 4, 0), // 2: B (Physical), next_sibling = A, first_child = B1
+            node(1, 0, 0, 0, 0), // 3: A1 (Physical), owns the higher-bit cap
+            node(2, 0, 0, 0, 0)  // 4: B1 (Physical), owns the lower-bit cap
+        ];
+        let caps_info_for_input = CapsInfo { first_cap: 0, number_of_caps: 2, last_cap: 2, report_byte_length: 0 };
+        let empty_caps_info = CapsInfo { first_cap: 0, number_of_caps: 0, last_cap: 0, report_byte_length: 0 };
+        let cap_a1 = Caps::for_test(1, 2, 8, 1, 3, 1, 0x30);
+        let cap_b1 = Caps::for_test(1, 1, 8, 1, 4, 1, 0x31);
+        let buf = RawBuffer {
+            header: HidpPreparsedData::for_test([caps_info_for_input, empty_caps_info, empty_caps_info], 0, nodes.len() as u16, [cap_a1]),
+            extra_caps: [cap_b1]
+        };
+        (buf, nodes)
+    }
+
+    #[test]
+    fn sibling_collections_are_ordered_by_propagated_bit_position() {
+        let (raw, nodes) = two_level_composite_descriptor();
+        let mut out = [0u8; 256];
+        let len = unsafe { get_descriptor_from_header(&raw.header as *const HidpPreparsedData, &nodes, &mut out) };
+
+        let descriptor = parser::parse(&out[..len]).unwrap();
+        assert_eq!(descriptor.collections.len(), 1);
+        let Node::Collection { children, .. } = &descriptor.collections[0] else {
+            panic!("expected a Collection node");
+        };
+        assert_eq!(children.len(), 2);
+
+        // Each direct child (A or B) holds its grandchild (A1 or B1) as its
+        // only child, which in turn owns the actual field.
+        fn grandchild_field_usage(node: &Node) -> crate::descriptor::FieldUsage {
+            let Node::Collection { children, .. } = node else {
+                panic!("expected a Collection node");
+            };
+            let Node::Collection { children, .. } = &children[0] else {
+                panic!("expected a Collection node");
+            };
+            let Node::Field(field) = &children[0] else {
+                panic!("expected a Field node");
+            };
+            field.usage
+        }
 
-        // TODO Implement the rest
-        // https://github.com/libusb/hidapi/blob/d0856c05cecbb1522c24fd2f1ed1e144b001f349/windows/hidapi_descriptor_reconstruct.c#L199
+        // B (owning the lower-bit field via B1) must be emitted before A,
+        // even though A comes before B in the unsorted child order.
+        assert_eq!(grandchild_field_usage(&children[0]), crate::descriptor::FieldUsage::Single(0x31));
+        assert_eq!(grandchild_field_usage(&children[1]), crate::descriptor::FieldUsage::Single(0x30));
     }
-    Ok(0)
 }
 
@@ -111,6 +111,20 @@ pub union MaybeRange {
 }
 
 
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+#[repr(u8)]
+pub enum ReportType {
+    Input = 0,
+    Output = 1,
+    Feature = 2
+}
+
+impl ReportType {
+    pub fn values() -> [ReportType; 3] {
+        [ReportType::Input, ReportType::Output, ReportType::Feature]
+    }
+}
+
 const_assert!(size_of::<Caps>() == 104);
 #[derive(Copy, Clone)]
 #[repr(C)]
@@ -137,6 +151,34 @@ pub struct Caps {
 }
 
 impl Caps {
+    /// Builds a non-button value cap with a single (non-range) usage, for
+    /// tests that need a hand-built `Caps` without reaching into its private
+    /// reserved fields.
+    #[cfg(test)]
+    pub(crate) fn for_test(report_id: u8, byte_position: u16, report_size: u16, report_count: u16, link_collection: u16, usage_page: Usage, usage: Usage) -> Self {
+        Caps {
+            usage_page,
+            report_id,
+            bit_position: 0,
+            report_size,
+            report_count,
+            byte_position,
+            bit_count: 0,
+            bit_field: 0x02, // Data, Variable, Absolute
+            next_byte_position: 0,
+            link_collection,
+            link_usage_page: usage_page,
+            link_usage: usage,
+            flags: 0,
+            _reserved: [0; 3],
+            unknown_tokens: [UnknownToken { token: 0, _reserved: [0; 3], bit_field: 0 }; 4],
+            maybe_range: MaybeRange { not_range: NotRange { usage, _reserved1: 0, string_index: 0, _reserved2: 0, designator_index: 0, _reserved3: 0, data_index: 0, _reserved4: 0 } },
+            maybe_button: MaybeButton { not_button: NotButton { has_nul: 0, _reserved: [0; 3], logical_min: 0, logical_max: 1, physical_min: 0, physical_max: 0 } },
+            units: 0,
+            units_exp: 0
+        }
+    }
+
     pub fn is_button_cap(&self) -> bool {
         self.flags & (1 << 2) != 0
     }
@@ -152,6 +194,11 @@ impl Caps {
     pub fn is_desginator_range(&self) -> bool {
         self.flags & (1 << 7) != 0
     }
+    /// Decodes `units`/`units_exp` into a structured physical unit. Returns
+    /// `None` if this cap doesn't carry a recognized HID unit system.
+    pub fn unit(&self) -> Option<crate::descriptor::units::HidUnit> {
+        crate::descriptor::units::HidUnit::from_raw(self.units, self.units_exp)
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -163,5 +210,30 @@ pub struct HidpPreparsedData {
     _reserved: [u16; 2],
     pub caps_info: [CapsInfo; 3],
     pub first_byte_of_link_collection_array: u16,
-    pub number_link_collection_nodes: u16
+    pub number_link_collection_nodes: u16,
+    // Start of the trailing, variable-length array of `Caps`; the link
+    // collection node array lies further on in memory (see
+    // `first_byte_of_link_collection_array`). Entries past index 0 are only
+    // read through raw pointer arithmetic, never through Rust's bounds-checked
+    // indexing.
+    pub caps: [Caps; 1]
+}
+
+#[cfg(test)]
+impl HidpPreparsedData {
+    /// Builds a header with a valid magic key and the given caps/link
+    /// collection bookkeeping fields, for tests that need a hand-built
+    /// `HidpPreparsedData` without reaching into its private reserved field.
+    pub(crate) fn for_test(caps_info: [CapsInfo; 3], first_byte_of_link_collection_array: u16, number_link_collection_nodes: u16, caps: [Caps; 1]) -> Self {
+        HidpPreparsedData {
+            magic_key: *b"HidP KDR",
+            usage: 0,
+            usage_page: 0,
+            _reserved: [0; 2],
+            caps_info,
+            first_byte_of_link_collection_array,
+            number_link_collection_nodes,
+            caps
+        }
+    }
 }